@@ -5,16 +5,20 @@
 
 //! Driver for the DFPlayer using the embedded-hal traits.
 
+use core::convert::TryFrom;
 use embedded_hal::serial::{Read, Write};
 use nb::block;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 /// Typealias for a message that is send and recived
-type Message = [u8; 10];
+pub type Message = [u8; 10];
 
 /// Constants
 const MSG_START: u8 = 0x7e;
 const MSG_END: u8 = 0xef;
+/// Number of `read_message` polls a blocking query waits for a matching reply
+/// before giving up with `Error::QueryTimeout`
+const QUERY_ATTEMPTS: u32 = 10_000;
 
 /// Error used in this crate
 #[derive(Debug)]
@@ -27,6 +31,14 @@ pub enum Error<TXE, RXE> {
     MessageNotComplete,
     /// Recived more than 8 chars after start byte
     MessageOverrun,
+    /// Checksum of the recived message does not match its content
+    ChecksumMismatch,
+    /// Recived a frame this driver does not know how to decode
+    UnknownEvent,
+    /// No matching reply was recived for a query before giving up
+    QueryTimeout,
+    /// The module rejected a command after exhausting all retries
+    CommandNack(State),
 }
 
 /// The DFPlayer Driver
@@ -35,6 +47,13 @@ pub struct DFPlayer<TX, RX> {
     tx: TX,
     rx_message: Message,
     rx_counter: u8,
+    feedback: bool,
+    retries: u8,
+    tx_message: Message,
+    tx_cursor: u8,
+    current_volume: Option<u8>,
+    fade_target: Option<u8>,
+    fade_step_ms: u16,
 }
 
 impl<TX, RX> DFPlayer<TX, RX>
@@ -49,63 +68,152 @@ where
             tx,
             rx_message: [0; 10],
             rx_counter: 0,
+            feedback: false,
+            retries: 3,
+            tx_message: [0; 10],
+            tx_cursor: 10,
+            current_volume: None,
+            fade_target: None,
+            fade_step_ms: 0,
         }
     }
 
+    /// Enables or disables waiting for an ACK/NACK reply after sending a command.
+    /// Disabled by default, matching the module's fire-and-forget behaviour.
+    pub fn set_feedback(&mut self, feedback: bool) {
+        self.feedback = feedback;
+    }
+
+    /// Sets how many times a command is retransmitted after a NACK or a missing
+    /// reply before giving up. Only relevant once `set_feedback(true)` is set.
+    pub fn set_retries(&mut self, retries: u8) {
+        self.retries = retries;
+    }
+
     /// Pause Playing a track
     pub fn pause(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::Pause.into())
+        self.send_command(Command::Pause.into())
     }
 
     /// Start Plaing a track
     pub fn play(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::Playback.into())
+        self.send_command(Command::Playback.into())
     }
 
     /// Next Track
     pub fn next_track(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::Next.into())
+        self.send_command(Command::Next.into())
     }
 
     /// Next Track
     pub fn previous_track(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::Previous.into())
+        self.send_command(Command::Previous.into())
     }
 
     /// Increse Volume by one
     pub fn increse_volume(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::IncreseVolume.into())
+        let current = self.known_volume()?;
+        self.send_command(Command::IncreseVolume.into())?;
+        self.current_volume = Some(current.min(30).saturating_add(1).min(30));
+        Ok(())
     }
 
     /// Increse Volume by one
     pub fn decrese_volume(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::DecreseVolume.into())
+        let current = self.known_volume()?;
+        self.send_command(Command::DecreseVolume.into())?;
+        self.current_volume = Some(current.saturating_sub(1));
+        Ok(())
+    }
+
+    /// Returns `current_volume`, blocking on [`query_volume`](Self::query_volume)
+    /// to learn the real hardware value the first time it's needed.
+    ///
+    /// The actual hardware volume is unknown until this driver has itself set
+    /// or read it at least once (e.g. right after `DFPlayer::new()`, or after
+    /// the module kept its volume across a power cycle). Assuming `0` in that
+    /// case would let a relative step (`increse_volume`/`decrese_volume`/a
+    /// fade) silently drift from the real volume.
+    fn known_volume(&mut self) -> Result<u8, Error<TX::Error, RX::Error>> {
+        match self.current_volume {
+            Some(vol) => Ok(vol),
+            None => {
+                let vol = self.query_volume()?;
+                self.current_volume = Some(vol);
+                Ok(vol)
+            }
+        }
     }
 
     /// Set the volume to specific value (0-30)
     /// Volume is limited to 0-30
     pub fn set_volume(&mut self, vol: u8) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::SpecifyVolume(vol.max(0).min(30)).into())
+        let vol = vol.max(0).min(30);
+        self.send_command(Command::SpecifyVolume(vol).into())?;
+        self.current_volume = Some(vol);
+        Ok(())
+    }
+
+    /// Set the volume as a percentage (0-100), mapped onto the hardware's 0-30 range
+    pub fn set_volume_percent(&mut self, pct: u8) -> Result<(), Error<TX::Error, RX::Error>> {
+        let vol = (pct.min(100) as f32 * 0.3).round() as u8;
+        self.set_volume(vol)
+    }
+
+    /// Starts a non-blocking fade of the volume to `target` (0-30), stepping by
+    /// one every `step_ms` milliseconds. Drive the fade by calling
+    /// [`tick`](Self::tick) from a timer every `step_ms`.
+    pub fn fade_to(&mut self, target: u8, step_ms: u16) {
+        self.fade_target = Some(target.min(30));
+        self.fade_step_ms = step_ms;
+    }
+
+    /// Delay between fade steps in milliseconds, for the caller's timer to schedule [`tick`](Self::tick)
+    pub fn fade_step_ms(&self) -> u16 {
+        self.fade_step_ms
+    }
+
+    /// Advances an in-progress [`fade_to`](Self::fade_to) by one step. A no-op
+    /// once the target volume is reached or no fade is in progress.
+    ///
+    /// The first `tick` of a fade may block on [`query_volume`](Self::query_volume)
+    /// via [`known_volume`](Self::known_volume) to learn the real starting
+    /// point, same as `increse_volume`/`decrese_volume`.
+    pub fn tick(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
+        let target = match self.fade_target {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+
+        let current = self.known_volume()?;
+
+        match current.cmp(&target) {
+            core::cmp::Ordering::Less => self.increse_volume()?,
+            core::cmp::Ordering::Greater => self.decrese_volume()?,
+            core::cmp::Ordering::Equal => self.fade_target = None,
+        }
+
+        Ok(())
     }
 
     /// Set DFPlayer to standby to reduce power consumption.
     pub fn standby(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::Standby.into())
+        self.send_command(Command::Standby.into())
     }
 
     /// Resets the DFPlayer
     pub fn reset_module(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::ResetModule.into())
+        self.send_command(Command::ResetModule.into())
     }
 
     /// Wakes DFPlayer from standby
     pub fn wakeup(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::NormalWorking.into())
+        self.send_command(Command::NormalWorking.into())
     }
 
     /// Sets the equilizer
     pub fn set_equilizer(&mut self, eq: Equalizer) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::SpecifyEqualizer(eq).into())
+        self.send_command(Command::SpecifyEqualizer(eq).into())
     }
 
     /// Sets the playback mode
@@ -113,12 +221,12 @@ where
         &mut self,
         mode: PlaybackMode,
     ) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::SpecifyPlaybackMode(mode).into())
+        self.send_command(Command::SpecifyPlaybackMode(mode).into())
     }
 
     /// Play a track from mp3 folder
     pub fn play_mp3(&mut self, track: u16) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::SpecifyMp3Track(track.min(9999).max(0)).into())
+        self.send_command(Command::SpecifyMp3Track(track.min(9999).max(0)).into())
     }
 
     /// Play a track from a folder. Folder is limited from 0-99 and track from 0-9999
@@ -127,23 +235,149 @@ where
         folder: u8,
         track: u8,
     ) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::SpecifyFolder(folder.min(99).max(0), track).into())
+        self.send_command(Command::SpecifyFolder(folder.min(99).max(0), track).into())
     }
 
     /// Pause Plaing, play advertisement, resume playing.
     pub fn advertise(&mut self, ad: u16) -> Result<(), Error<TX::Error, RX::Error>> {
-        self.send_message(Command::SpecifyAdvertisement(ad.min(9999).max(0)).into())
+        self.send_command(Command::SpecifyAdvertisement(ad.min(9999).max(0)).into())
     }
 
     /// Recive a message from dfplayer. Can be called cyclic or in an interrupt. Reads until 10 bytes arrive or timeout occures
     pub fn get_message(&mut self) {}
 
+    /// Decode a completed frame returned by [`read_message`](Self::read_message) into a typed [`Event`].
+    ///
+    /// Recomputes the checksum over bytes `[1..7]` and compares it against the
+    /// checksum carried in bytes `[7..9]`, returning `Error::ChecksumMismatch` if
+    /// they don't agree. This lets callers react to track-finished and hot-plug
+    /// notifications without matching raw command bytes themselves.
+    pub fn decode(&self, msg: Message) -> Result<Event, Error<TX::Error, RX::Error>> {
+        let expected = checksum(&msg).to_be_bytes();
+        if msg[7..9] != expected {
+            return Err(Error::ChecksumMismatch);
+        }
+
+        let track = u16::from_be_bytes([msg[5], msg[6]]);
+
+        match msg[3] {
+            0x3C => Ok(Event::TrackFinished {
+                device: Device::UDisk,
+                track,
+            }),
+            0x3D => Ok(Event::TrackFinished {
+                device: Device::SD,
+                track,
+            }),
+            0x3E => Ok(Event::TrackFinished {
+                device: Device::Flash,
+                track,
+            }),
+            0x3A => Device::try_from(msg[6])
+                .map(Event::DeviceInserted)
+                .map_err(|_| Error::UnknownEvent),
+            0x3B => Device::try_from(msg[6])
+                .map(Event::DeviceEjected)
+                .map_err(|_| Error::UnknownEvent),
+            0x3F => Device::try_from(msg[6])
+                .map(Event::DeviceOnline)
+                .map_err(|_| Error::UnknownEvent),
+            0x41 => Ok(Event::Ack),
+            0x40 => State::try_from(msg[6])
+                .map(Event::ModuleError)
+                .map_err(|_| Error::UnknownEvent),
+            _ => Err(Error::UnknownEvent),
+        }
+    }
+
+    /// Sends a command frame, honouring `feedback`/`retries`.
+    ///
+    /// When feedback is disabled this just writes the frame once. When enabled,
+    /// byte 4 is set to request feedback and the frame's checksum is recomputed,
+    /// then the driver waits for an ACK (`0x41`). A NACK (`0x40`) or a missing
+    /// reply resends the frame, up to `retries` times, before surfacing
+    /// `Error::CommandNack` or `Error::QueryTimeout`.
+    fn send_command(&mut self, mut msg: Message) -> Result<(), Error<TX::Error, RX::Error>> {
+        if self.feedback {
+            msg[4] = 0x01;
+            add_checksum(&mut msg);
+        }
+
+        let mut attempt = 0;
+        loop {
+            self.send_message(msg)?;
+
+            if !self.feedback {
+                return Ok(());
+            }
+
+            match self.wait_for_ack() {
+                Ok(()) => return Ok(()),
+                Err(Error::CommandNack(_)) | Err(Error::QueryTimeout) | Err(Error::ChecksumMismatch)
+                    if attempt < self.retries =>
+                {
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Blocks until an ACK (`0x41`) or NACK (`0x40`) frame is recived. A frame
+    /// whose checksum doesn't check out is never trusted enough to be read as
+    /// an ACK/NACK; `send_command` treats that the same as a missing reply and
+    /// resends.
+    fn wait_for_ack(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
+        for _ in 0..QUERY_ATTEMPTS {
+            match self.read_message() {
+                Ok(msg) if !checksum_ok(&msg) => return Err(Error::ChecksumMismatch),
+                Ok(msg) if msg[3] == 0x41 => return Ok(()),
+                Ok(msg) if msg[3] == 0x40 => {
+                    let state = State::try_from(msg[6]).map_err(|_| Error::UnknownEvent)?;
+                    return Err(Error::CommandNack(state));
+                }
+                Ok(_)
+                | Err(Error::MessageNotComplete)
+                | Err(Error::ReadError(nb::Error::WouldBlock)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(Error::QueryTimeout)
+    }
+
     /// Send a message
     fn send_message(&mut self, msg: Message) -> Result<(), Error<TX::Error, RX::Error>> {
-        for byte in msg.iter() {
-            if let Err(err) = block!(self.tx.write(*byte)) {
-                return Err(Error::WriteError(err));
-            }
+        self.load_message(msg);
+        block!(self.poll_send())
+    }
+
+    /// Buffers `msg` for transmission without blocking, to be drained by
+    /// repeatedly calling [`poll_send`](Self::poll_send) from a caller's own
+    /// cooperative or interrupt-driven loop.
+    ///
+    /// This is the low-level counterpart to the blocking command methods
+    /// (`play`, `pause`, ...), which internally call `load_message` and then
+    /// drain it with `block!` right away. Use this directly when that `block!`
+    /// isn't acceptable, e.g. to queue `Command::Playback.into()` and poll it
+    /// to completion alongside other peripherals on a single-threaded executor.
+    /// Bypasses the feedback/ACK/retry machinery from `set_feedback`.
+    pub fn load_message(&mut self, msg: Message) {
+        self.tx_message = msg;
+        self.tx_cursor = 0;
+    }
+
+    /// Writes as many bytes of the buffered message as the serial peripheral
+    /// currently accepts, returning `nb::Error::WouldBlock` until the whole
+    /// frame has been sent. Call [`load_message`](Self::load_message) (or one
+    /// of the command methods) first to buffer a new frame; calling this with
+    /// nothing buffered is a no-op that returns `Ok(())`.
+    pub fn poll_send(&mut self) -> nb::Result<(), Error<TX::Error, RX::Error>> {
+        while (self.tx_cursor as usize) < self.tx_message.len() {
+            self.tx
+                .write(self.tx_message[self.tx_cursor as usize])
+                .map_err(|err| err.map(Error::WriteError))?;
+            self.tx_cursor += 1;
         }
         Ok(())
     }
@@ -176,11 +410,120 @@ where
 
         Err(Error::MessageNotComplete)
     }
+
+    /// Sends a query and blocks until a matching response arrives, returning
+    /// its raw `u16` payload from bytes `[5..7]`, or `Error::QueryTimeout` if
+    /// no matching reply shows up within `QUERY_ATTEMPTS` reads. A frame whose
+    /// checksum doesn't check out is never trusted enough to be read as a
+    /// reply, since its command byte could just as well be corrupted into
+    /// matching `expected_cmd`.
+    fn query_raw(
+        &mut self,
+        querry: Querry,
+        expected_cmd: u8,
+    ) -> Result<u16, Error<TX::Error, RX::Error>> {
+        self.send_message(querry.into())?;
+
+        for _ in 0..QUERY_ATTEMPTS {
+            match self.read_message() {
+                Ok(msg) if !checksum_ok(&msg) => return Err(Error::ChecksumMismatch),
+                Ok(msg) if msg[3] == expected_cmd => {
+                    return Ok(u16::from_be_bytes([msg[5], msg[6]]));
+                }
+                Ok(_)
+                | Err(Error::MessageNotComplete)
+                | Err(Error::ReadError(nb::Error::WouldBlock)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(Error::QueryTimeout)
+    }
+
+    /// Requests the current playback status without waiting for the reply
+    pub fn request_status(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
+        self.send_message(Querry::Status.into())
+    }
+
+    /// Blocks until the current playback status is received
+    pub fn query_state(&mut self) -> Result<State, Error<TX::Error, RX::Error>> {
+        let raw = self.query_raw(Querry::Status, 0x42)?;
+        State::try_from(raw as u8).map_err(|_| Error::UnknownEvent)
+    }
+
+    /// Requests the current volume without waiting for the reply
+    pub fn request_volume(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
+        self.send_message(Querry::Volume.into())
+    }
+
+    /// Blocks until the current volume (0-30) is received
+    pub fn query_volume(&mut self) -> Result<u8, Error<TX::Error, RX::Error>> {
+        Ok(self.query_raw(Querry::Volume, 0x43)? as u8)
+    }
+
+    /// Requests the current equalizer setting without waiting for the reply
+    pub fn request_equalizer(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
+        self.send_message(Querry::Equalizer.into())
+    }
+
+    /// Blocks until the current equalizer setting is received
+    pub fn query_equalizer(&mut self) -> Result<Equalizer, Error<TX::Error, RX::Error>> {
+        let raw = self.query_raw(Querry::Equalizer, 0x44)?;
+        Equalizer::try_from(raw as u8).map_err(|_| Error::UnknownEvent)
+    }
+
+    /// Requests the current playback mode without waiting for the reply
+    pub fn request_playback_mode(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
+        self.send_message(Querry::PlaybackMode.into())
+    }
+
+    /// Blocks until the current playback mode is received
+    pub fn query_playback_mode(&mut self) -> Result<PlaybackMode, Error<TX::Error, RX::Error>> {
+        let raw = self.query_raw(Querry::PlaybackMode, 0x45)?;
+        PlaybackMode::try_from(raw as u8).map_err(|_| Error::UnknownEvent)
+    }
+
+    /// Requests the module's software version without waiting for the reply
+    pub fn request_software_version(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
+        self.send_message(Querry::SoftwareVersion.into())
+    }
+
+    /// Blocks until the module's software version is received
+    pub fn query_software_version(&mut self) -> Result<u16, Error<TX::Error, RX::Error>> {
+        self.query_raw(Querry::SoftwareVersion, 0x46)
+    }
+
+    /// Requests the number of files in a folder without waiting for the reply
+    pub fn request_file_count_in_folder(
+        &mut self,
+        folder: u8,
+    ) -> Result<(), Error<TX::Error, RX::Error>> {
+        self.send_message(Querry::FileCountInFolder(folder).into())
+    }
+
+    /// Blocks until the number of files in `folder` is received
+    pub fn query_file_count_in_folder(
+        &mut self,
+        folder: u8,
+    ) -> Result<u16, Error<TX::Error, RX::Error>> {
+        self.query_raw(Querry::FileCountInFolder(folder), 0x4E)
+    }
+
+    /// Requests the number of folders on the current device without waiting for the reply
+    pub fn request_folder_count(&mut self) -> Result<(), Error<TX::Error, RX::Error>> {
+        self.send_message(Querry::FolderCount.into())
+    }
+
+    /// Blocks until the number of folders on the current device is received
+    pub fn query_folder_count(&mut self) -> Result<u16, Error<TX::Error, RX::Error>> {
+        self.query_raw(Querry::FolderCount, 0x4F)
+    }
 }
 
 /// Representing a message to the tag
+#[allow(missing_docs)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-enum Command {
+pub enum Command {
     Next,
     Previous,
     /// Set a track (0-2999)
@@ -259,7 +602,8 @@ enum Querry {
     Equalizer,
     PlaybackMode,
     SoftwareVersion,
-    FileCountInFolder,
+    /// Number of files in the given folder (0-99)
+    FileCountInFolder(u8),
     FolderCount,
 }
 
@@ -271,14 +615,23 @@ impl core::convert::From<Querry> for Message {
         add_static_bytes(&mut msg);
 
         msg[3] = match querry {
-            Querry::Volume => 0x01,
-            _ => unimplemented!(),
+            Querry::Status => 0x42,
+            Querry::Volume => 0x43,
+            Querry::Equalizer => 0x44,
+            Querry::PlaybackMode => 0x45,
+            Querry::SoftwareVersion => 0x46,
+            Querry::FileCountInFolder(_) => 0x4E,
+            Querry::FolderCount => 0x4F,
         };
 
         msg[4] = 0x00; // Is a command --> We want no feedback
 
-        msg[5] = 0x00;
-        msg[6] = 0x00;
+        let data = match querry {
+            Querry::FileCountInFolder(folder) => [folder, 0x00],
+            _ => [0x00, 0x00],
+        };
+        msg[5] = data[0];
+        msg[6] = data[1];
 
         add_checksum(&mut msg);
 
@@ -286,17 +639,28 @@ impl core::convert::From<Querry> for Message {
     }
 }
 
-/// Calculate the checksum
-fn add_checksum(msg: &mut [u8]) {
+/// Calculate the checksum over bytes `[1..7]` of a message
+fn checksum(msg: &[u8]) -> u16 {
     let mut sum: u16 = 0;
     for &byte in msg[1..7].iter() {
         sum += byte as u16;
     }
 
-    let checksum = (0_u16.wrapping_sub(sum)).to_be_bytes();
+    0_u16.wrapping_sub(sum)
+}
+
+/// Calculate the checksum and write it into a message
+fn add_checksum(msg: &mut [u8]) {
+    let checksum = checksum(msg).to_be_bytes();
     msg[7..9].copy_from_slice(&checksum);
 }
 
+/// Checks a recived message's checksum (bytes `[7..9]`) against the one
+/// computed over its content (bytes `[1..7]`)
+fn checksum_ok(msg: &[u8]) -> bool {
+    checksum(msg).to_be_bytes() == msg[7..9]
+}
+
 /// Adds static bytes to message
 fn add_static_bytes(msg: &mut Message) {
     msg[0] = 0x7e; // Start Byte
@@ -364,3 +728,486 @@ pub enum Device {
     Sleep = 0x04,
     Flash = 0x05,
 }
+
+/// Asynchronous notifications sent by the DFPlayer, decoded from a raw frame
+/// by [`DFPlayer::decode`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Event {
+    /// A track finished playing on the given device
+    TrackFinished {
+        /// Device the track finished playing on
+        device: Device,
+        /// Index of the finished track
+        track: u16,
+    },
+    /// A storage device was inserted
+    DeviceInserted(Device),
+    /// A storage device was removed
+    DeviceEjected(Device),
+    /// A storage device came online
+    DeviceOnline(Device),
+    /// A command was acknowledged
+    Ack,
+    /// The module reported an error state
+    ModuleError(State),
+}
+
+/// A `(folder, track)` pair identifying a single playable file
+pub type Track = (u8, u8);
+
+/// Software playlist and play-history manager layered on top of the raw
+/// `DFPlayer` commands, giving gapless programmatic sequencing that the bare
+/// hardware next/previous buttons can't express.
+///
+/// `N` is the fixed capacity of both the pending queue and the history.
+/// `history_index` counts how many steps back through history are still
+/// available, `0` meaning history is depleted and `play_previous` should fall
+/// back to the hardware command.
+pub struct Playlist<const N: usize> {
+    queue: [Track; N],
+    queue_len: usize,
+    current: Option<Track>,
+    history: [Track; N],
+    history_len: usize,
+    history_index: usize,
+}
+
+impl<const N: usize> Playlist<N> {
+    /// Creates an empty playlist
+    pub fn new() -> Self {
+        Self {
+            queue: [(0, 0); N],
+            queue_len: 0,
+            current: None,
+            history: [(0, 0); N],
+            history_len: 0,
+            history_index: 0,
+        }
+    }
+
+    /// Appends a `(folder, track)` pair to the queue. Returns `false` if the queue is full.
+    pub fn enqueue(&mut self, folder: u8, track: u8) -> bool {
+        if self.queue_len == N {
+            return false;
+        }
+        self.queue[self.queue_len] = (folder, track);
+        self.queue_len += 1;
+        true
+    }
+
+    /// Pops the oldest queued entry, shifting the remaining ones forward
+    fn dequeue(&mut self) -> Option<Track> {
+        if self.queue_len == 0 {
+            return None;
+        }
+        let item = self.queue[0];
+        for i in 1..self.queue_len {
+            self.queue[i - 1] = self.queue[i];
+        }
+        self.queue_len -= 1;
+        Some(item)
+    }
+
+    /// Files `item` into history, dropping the oldest entry once full.
+    ///
+    /// First truncates away anything beyond `history_index`: once
+    /// `play_previous` has walked back, those trailing entries are stale —
+    /// they were already stepped through on the way back and would otherwise
+    /// linger as duplicates once playback branches off in a new direction.
+    fn push_history(&mut self, item: Track) {
+        self.history_len = self.history_index;
+
+        if self.history_len == N {
+            for i in 1..N {
+                self.history[i - 1] = self.history[i];
+            }
+            self.history[N - 1] = item;
+        } else {
+            self.history[self.history_len] = item;
+            self.history_len += 1;
+        }
+        self.history_index = self.history_len;
+    }
+
+    /// Pops the next queued track, plays it and files the currently playing
+    /// track into history. Returns `Ok(false)` without sending anything if the
+    /// queue is empty, so callers can auto-advance from `Event::TrackFinished`.
+    pub fn play_next<TX, RX>(
+        &mut self,
+        player: &mut DFPlayer<TX, RX>,
+    ) -> Result<bool, Error<TX::Error, RX::Error>>
+    where
+        RX: Read<u8>,
+        TX: Write<u8>,
+    {
+        let next = match self.dequeue() {
+            Some(item) => item,
+            None => return Ok(false),
+        };
+
+        if let Some(previous) = self.current {
+            self.push_history(previous);
+        }
+
+        player.play_folder_track(next.0, next.1)?;
+        self.current = Some(next);
+        Ok(true)
+    }
+
+    /// Re-plays the previous track from history, walking further back on
+    /// repeated calls. Once history is depleted, falls back to the hardware
+    /// `Previous` command.
+    pub fn play_previous<TX, RX>(
+        &mut self,
+        player: &mut DFPlayer<TX, RX>,
+    ) -> Result<(), Error<TX::Error, RX::Error>>
+    where
+        RX: Read<u8>,
+        TX: Write<u8>,
+    {
+        if self.history_index == 0 {
+            return player.previous_track();
+        }
+
+        self.history_index -= 1;
+        let item = self.history[self.history_index];
+        player.play_folder_track(item.0, item.1)?;
+        self.current = Some(item);
+        Ok(())
+    }
+}
+
+impl<const N: usize> Default for Playlist<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A serial mock that never has anything to read and always accepts writes
+    struct NullSerial;
+
+    impl Read<u8> for NullSerial {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    impl Write<u8> for NullSerial {
+        type Error = ();
+
+        fn write(&mut self, _byte: u8) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    fn player() -> DFPlayer<NullSerial, NullSerial> {
+        DFPlayer::new(NullSerial, NullSerial)
+    }
+
+    /// Builds a well-formed frame with the given command byte and `u16` param
+    fn framed(cmd: u8, param: u16) -> Message {
+        let mut msg = [0u8; 10];
+        msg[0] = MSG_START;
+        msg[1] = 0xFF;
+        msg[2] = 0x06;
+        msg[3] = cmd;
+        let param = param.to_be_bytes();
+        msg[5] = param[0];
+        msg[6] = param[1];
+        add_checksum(&mut msg);
+        msg[9] = MSG_END;
+        msg
+    }
+
+    #[test]
+    fn decode_rejects_checksum_mismatch() {
+        let mut msg = framed(0x41, 0);
+        msg[7] ^= 0xFF;
+        assert!(matches!(
+            player().decode(msg),
+            Err(Error::ChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn decode_rejects_unknown_command() {
+        assert!(matches!(
+            player().decode(framed(0x99, 0)),
+            Err(Error::UnknownEvent)
+        ));
+    }
+
+    #[test]
+    fn decode_track_finished_udisk() {
+        assert_eq!(
+            player().decode(framed(0x3C, 42)).unwrap(),
+            Event::TrackFinished {
+                device: Device::UDisk,
+                track: 42
+            }
+        );
+    }
+
+    #[test]
+    fn decode_track_finished_sd() {
+        assert_eq!(
+            player().decode(framed(0x3D, 7)).unwrap(),
+            Event::TrackFinished {
+                device: Device::SD,
+                track: 7
+            }
+        );
+    }
+
+    #[test]
+    fn decode_track_finished_flash() {
+        assert_eq!(
+            player().decode(framed(0x3E, 1)).unwrap(),
+            Event::TrackFinished {
+                device: Device::Flash,
+                track: 1
+            }
+        );
+    }
+
+    #[test]
+    fn decode_device_inserted() {
+        assert_eq!(
+            player().decode(framed(0x3A, Device::SD as u16)).unwrap(),
+            Event::DeviceInserted(Device::SD)
+        );
+    }
+
+    #[test]
+    fn decode_device_ejected() {
+        assert_eq!(
+            player().decode(framed(0x3B, Device::UDisk as u16)).unwrap(),
+            Event::DeviceEjected(Device::UDisk)
+        );
+    }
+
+    #[test]
+    fn decode_device_online() {
+        assert_eq!(
+            player().decode(framed(0x3F, Device::Flash as u16)).unwrap(),
+            Event::DeviceOnline(Device::Flash)
+        );
+    }
+
+    #[test]
+    fn decode_ack() {
+        assert_eq!(player().decode(framed(0x41, 0)).unwrap(), Event::Ack);
+    }
+
+    #[test]
+    fn decode_module_error() {
+        assert_eq!(
+            player()
+                .decode(framed(0x40, State::FileMismatch as u16))
+                .unwrap(),
+            Event::ModuleError(State::FileMismatch)
+        );
+    }
+
+    #[test]
+    fn play_next_returns_false_on_empty_queue() {
+        let mut playlist: Playlist<4> = Playlist::new();
+        let mut p = player();
+        assert!(!playlist.play_next(&mut p).unwrap());
+    }
+
+    #[test]
+    fn play_previous_falls_back_to_hardware_once_history_is_depleted() {
+        let mut playlist: Playlist<4> = Playlist::new();
+        let mut p = player();
+
+        playlist.enqueue(1, 1);
+        playlist.play_next(&mut p).unwrap();
+
+        // No history yet (only one track has ever played), so this should fall
+        // back to the hardware `Previous` command rather than replay anything.
+        assert!(playlist.play_previous(&mut p).is_ok());
+        assert_eq!(playlist.history_index, 0);
+    }
+
+    #[test]
+    fn play_next_after_play_previous_truncates_stale_history() {
+        let mut playlist: Playlist<4> = Playlist::new();
+        let mut p = player();
+
+        playlist.enqueue(1, 1);
+        playlist.enqueue(1, 2);
+        playlist.enqueue(1, 3);
+        playlist.play_next(&mut p).unwrap(); // current = (1, 1)
+        playlist.play_next(&mut p).unwrap(); // current = (1, 2), history = [(1, 1)]
+        playlist.play_next(&mut p).unwrap(); // current = (1, 3), history = [(1, 1), (1, 2)]
+
+        playlist.play_previous(&mut p).unwrap(); // replays (1, 2), history_index = 1
+
+        // Branching off here instead of continuing to walk history should
+        // drop the stale trailing (1, 2) entry rather than append a duplicate past it.
+        playlist.enqueue(1, 4);
+        playlist.play_next(&mut p).unwrap();
+
+        assert_eq!(playlist.history_len, 2);
+        assert_eq!(playlist.history[..2], [(1, 1), (1, 2)]);
+    }
+
+    /// Serial mock that replays a scripted sequence of `read()` outcomes.
+    /// `None` entries surface as `WouldBlock`, mimicking a UART whose reply
+    /// hasn't fully arrived yet; `Some(byte)` entries are yielded in order.
+    /// Once the script is consumed, every further read is `WouldBlock`.
+    struct ScriptedSerial<const N: usize> {
+        script: [Option<u8>; N],
+        pos: usize,
+    }
+
+    impl<const N: usize> ScriptedSerial<N> {
+        fn new(script: [Option<u8>; N]) -> Self {
+            Self { script, pos: 0 }
+        }
+    }
+
+    impl<const N: usize> Read<u8> for ScriptedSerial<N> {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            if self.pos >= N {
+                return Err(nb::Error::WouldBlock);
+            }
+            let item = self.script[self.pos];
+            self.pos += 1;
+            item.ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl<const N: usize> Write<u8> for ScriptedSerial<N> {
+        type Error = ();
+
+        fn write(&mut self, _byte: u8) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// Wraps a well-formed frame's bytes as `Some`, for use in a
+    /// `ScriptedSerial` script, with a `WouldBlock` spliced in first to mimic
+    /// the reply not having arrived on the very first poll.
+    fn scripted_frame(msg: Message) -> [Option<u8>; 11] {
+        let mut script = [None; 11];
+        for (i, byte) in msg.iter().enumerate() {
+            script[i + 1] = Some(*byte);
+        }
+        script
+    }
+
+    #[test]
+    fn send_command_succeeds_on_ack() {
+        let rx = ScriptedSerial::new(scripted_frame(framed(0x41, 0)));
+        let mut p = DFPlayer::new(NullSerial, rx);
+        p.set_feedback(true);
+        assert!(p.pause().is_ok());
+    }
+
+    #[test]
+    fn send_command_retries_after_nack_then_succeeds() {
+        let mut script = [None; 22];
+        script[1..11].copy_from_slice(&scripted_frame(framed(0x40, State::Busy as u16))[1..]);
+        script[12..22].copy_from_slice(&scripted_frame(framed(0x41, 0))[1..]);
+        let rx = ScriptedSerial::new(script);
+        let mut p = DFPlayer::new(NullSerial, rx);
+        p.set_feedback(true);
+        p.set_retries(1);
+        assert!(p.pause().is_ok());
+    }
+
+    #[test]
+    fn send_command_gives_up_as_command_nack_after_exhausting_retries() {
+        let frame = scripted_frame(framed(0x40, State::Busy as u16));
+        let mut script = [None; 22];
+        script[0..11].copy_from_slice(&frame);
+        script[11..22].copy_from_slice(&frame);
+        let rx = ScriptedSerial::new(script);
+        let mut p = DFPlayer::new(NullSerial, rx);
+        p.set_feedback(true);
+        p.set_retries(1);
+        assert!(matches!(p.pause(), Err(Error::CommandNack(State::Busy))));
+    }
+
+    /// Serial mock whose `write` accepts only the first `accept` bytes before
+    /// reporting `WouldBlock`, to exercise `poll_send` draining a message
+    /// across more than one call.
+    struct LimitedWrite {
+        accept: usize,
+        written: usize,
+    }
+
+    impl Read<u8> for LimitedWrite {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    impl Write<u8> for LimitedWrite {
+        type Error = ();
+
+        fn write(&mut self, _byte: u8) -> nb::Result<(), Self::Error> {
+            if self.written >= self.accept {
+                return Err(nb::Error::WouldBlock);
+            }
+            self.written += 1;
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn poll_send_reports_would_block_until_the_whole_message_is_drained() {
+        let tx = LimitedWrite {
+            accept: 3,
+            written: 0,
+        };
+        let mut p = DFPlayer::new(tx, NullSerial);
+        p.load_message(framed(0x0D, 0));
+
+        assert!(matches!(p.poll_send(), Err(nb::Error::WouldBlock)));
+        p.tx.accept = 10;
+        assert!(p.poll_send().is_ok());
+    }
+
+    #[test]
+    fn load_message_mid_drain_restarts_from_the_new_message() {
+        let tx = LimitedWrite {
+            accept: 3,
+            written: 0,
+        };
+        let mut p = DFPlayer::new(tx, NullSerial);
+        p.load_message(framed(0x0D, 0));
+        assert!(matches!(p.poll_send(), Err(nb::Error::WouldBlock)));
+
+        // Re-entrant load_message should discard the partially drained
+        // message and start the new one from scratch.
+        p.load_message(framed(0x0E, 0));
+        p.tx.written = 0;
+        p.tx.accept = 10;
+        assert!(p.poll_send().is_ok());
+    }
+}